@@ -0,0 +1,249 @@
+//! An in-memory, verifiable header chain used by the `checkpoint-sync` subcommand.
+//!
+//! Unlike the rest of `reth p2p`, which fetches a single item (or, since the range mode, a
+//! contiguous run of items) and prints it, checkpoint sync walks a peer's headers backward from a
+//! trusted hash and keeps only what's needed to prove the walked range is canonical: the headers
+//! themselves, indexed for link verification, and a running set of Canonical Hash Trie (CHT)
+//! roots computed every [`CHT_SECTION_SIZE`] blocks.
+
+use std::collections::{BTreeMap, HashMap};
+
+use alloy_primitives::{B256, U256};
+use alloy_rlp::Encodable;
+use alloy_trie::{HashBuilder, Nibbles};
+use reth_ethereum_primitives::Header;
+
+/// The number of blocks covered by a single CHT section, matching the original CHT spec used by
+/// go-ethereum's LES implementation. Section `s` covers block numbers `[s * CHT_SECTION_SIZE,
+/// (s + 1) * CHT_SECTION_SIZE - 1]`.
+pub const CHT_SECTION_SIZE: u64 = 2048;
+
+/// One or more competing headers at a given block number, kept around until the walk confirms
+/// which one (if any) is an ancestor of the trusted tip.
+#[derive(Debug, Default, Clone)]
+pub struct Entry {
+    /// Candidate headers seen at this height, keyed by hash for dedup.
+    candidates: HashMap<B256, Header>,
+}
+
+impl Entry {
+    /// Returns the candidate headers at this height.
+    pub fn candidates(&self) -> impl Iterator<Item = &Header> {
+        self.candidates.values()
+    }
+}
+
+/// Describes the highest-total-difficulty header seen so far.
+#[derive(Debug, Clone, Copy)]
+pub struct BestBlock {
+    /// The block number of the best header.
+    pub number: u64,
+    /// The hash of the best header.
+    pub hash: B256,
+    /// The total difficulty at the best header.
+    pub total_difficulty: U256,
+}
+
+/// A verifiable, in-memory header chain assembled while walking backward from a trusted hash.
+///
+/// Headers are linked by `parent_hash`: a header is only accepted if it either extends the
+/// trusted tip, or its hash equals the `parent_hash` of an already-accepted header. This lets the
+/// walk tolerate uncles/competing branches the peer might serve without ever trusting an
+/// unconnected header.
+#[derive(Debug, Default)]
+pub struct HeaderChain {
+    /// Candidate headers by block number, in ascending order.
+    entries: BTreeMap<u64, Entry>,
+    /// All accepted headers, indexed by hash for O(1) parent lookups.
+    by_hash: HashMap<B256, Header>,
+    /// Total difficulty at each accepted header, indexed by hash. This is the true cumulative
+    /// total difficulty (not the header's own `difficulty` field), derived during the walk from
+    /// the trusted tip's known total difficulty.
+    total_difficulties: HashMap<B256, U256>,
+    /// The highest-total-difficulty header accepted so far.
+    best_block: Option<BestBlock>,
+    /// CHT roots accumulated every [`CHT_SECTION_SIZE`] blocks, oldest first.
+    cht_roots: Vec<B256>,
+    /// The block number of the trusted tip the walk started from. A section can only be sealed
+    /// once it lies entirely below this number, since nothing above it is ever fetched.
+    tip_number: u64,
+}
+
+impl HeaderChain {
+    /// Creates an empty header chain seeded with the trusted tip and its known total difficulty.
+    /// The trusted header is accepted unconditionally; everything walked afterward must link back
+    /// to it.
+    pub fn new(trusted: Header, total_difficulty: U256) -> Self {
+        let mut chain = Self { tip_number: trusted.number, ..Self::default() };
+        chain.insert(trusted, total_difficulty);
+        chain
+    }
+
+    fn insert(&mut self, header: Header, total_difficulty: U256) {
+        let hash = header.hash_slow();
+        let number = header.number;
+
+        if total_difficulty > self.best_block.map_or(U256::ZERO, |b| b.total_difficulty) {
+            self.best_block = Some(BestBlock { number, hash, total_difficulty });
+        }
+
+        self.total_difficulties.insert(hash, total_difficulty);
+        self.by_hash.insert(hash, header.clone());
+        self.entries.entry(number).or_default().candidates.insert(hash, header);
+    }
+
+    /// Attempts to insert `header` as the parent of an already-known header at `child_number`.
+    ///
+    /// Returns `Ok(())` if `header`'s hash matches the `parent_hash` of at least one accepted
+    /// header at `child_number`, in which case it's recorded as a new candidate at
+    /// `header.number` with total difficulty `child_total_difficulty - child_difficulty` (since
+    /// total difficulty accumulates by adding each block's own difficulty to its parent's). A CHT
+    /// root is sealed once `header.number` is the lower bound of a section whose upper bound is
+    /// at or below the trusted tip, since only then is every block in `[number, number +
+    /// CHT_SECTION_SIZE - 1]` guaranteed to have already been walked and inserted; the section
+    /// the tip itself falls in is sealed only when the tip is its last block, as otherwise its
+    /// upper part is never fetched. Returns an
+    /// error if `header` doesn't extend any known candidate, meaning the peer served a header for
+    /// an unrelated fork.
+    pub fn insert_parent(
+        &mut self,
+        header: Header,
+        child_number: u64,
+        child_difficulty: U256,
+        child_total_difficulty: U256,
+    ) -> eyre::Result<()> {
+        let hash = header.hash_slow();
+        let links = self
+            .entries
+            .get(&child_number)
+            .into_iter()
+            .flat_map(|entry| entry.candidates())
+            .any(|child| child.parent_hash == hash);
+
+        if !links {
+            eyre::bail!(
+                "header {hash} (number {}) does not extend any known candidate at height {child_number}",
+                header.number
+            )
+        }
+
+        let number = header.number;
+        let total_difficulty = child_total_difficulty - child_difficulty;
+        self.insert(header, total_difficulty);
+
+        // `number` is the lower bound of the section `[number, number + CHT_SECTION_SIZE - 1]`.
+        // That section is only guaranteed fully present once its upper bound is at or below the
+        // trusted tip; the section containing the tip has an unfetched upper part, and so is
+        // never sealed, unless the tip is its last block.
+        if number % CHT_SECTION_SIZE == 0 {
+            let section_end = number + CHT_SECTION_SIZE - 1;
+            if section_end <= self.tip_number {
+                // The walk descends, so each newly sealed section is older than every one before.
+                let root = self.section_root(number, section_end)?;
+                self.cht_roots.insert(0, root);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the best (highest total difficulty) block seen so far.
+    pub fn best_block(&self) -> Option<BestBlock> {
+        self.best_block
+    }
+
+    /// Returns the CHT roots sealed so far, oldest section first.
+    pub fn cht_roots(&self) -> &[B256] {
+        &self.cht_roots
+    }
+
+    /// Computes the CHT root for the section `[section_start, section_end]` (inclusive).
+    ///
+    /// Each leaf maps a big-endian-encoded block number to `rlp((block_hash, total_difficulty))`,
+    /// matching the canonical CHT leaf encoding. Where a block number holds more than one
+    /// candidate, the first one its unordered candidate map yields is used; which of them that
+    /// is, is not defined.
+    fn section_root(&self, section_start: u64, section_end: u64) -> eyre::Result<B256> {
+        let mut builder = HashBuilder::default();
+
+        for number in section_start..=section_end {
+            let entry = self
+                .entries
+                .get(&number)
+                .ok_or_else(|| eyre::eyre!("missing header {number} while sealing CHT section"))?;
+            let header = entry
+                .candidates()
+                .next()
+                .ok_or_else(|| eyre::eyre!("no candidate header at {number}"))?;
+            let hash = header.hash_slow();
+            let total_difficulty = *self
+                .total_difficulties
+                .get(&hash)
+                .ok_or_else(|| eyre::eyre!("missing total difficulty for header {hash}"))?;
+
+            let mut value = Vec::new();
+            (hash, total_difficulty).encode(&mut value);
+
+            builder.add_leaf(Nibbles::unpack(number.to_be_bytes()), &value);
+        }
+
+        Ok(builder.root())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a contiguous chain of `len` headers numbered `0..len`, correctly linked by
+    /// `parent_hash`.
+    fn build_chain(len: u64) -> Vec<Header> {
+        let mut headers = Vec::with_capacity(len as usize);
+        let mut parent_hash = B256::ZERO;
+        for number in 0..len {
+            let header = Header { number, parent_hash, difficulty: U256::from(1), ..Default::default() };
+            parent_hash = header.hash_slow();
+            headers.push(header);
+        }
+        headers
+    }
+
+    #[test]
+    fn seals_a_section_only_once_it_is_fully_below_the_tip() {
+        // The trusted tip sits one block past a section boundary, so the walk crosses two
+        // boundaries: the tip's own section (never fully fetched) and the one below it.
+        let tip_number = CHT_SECTION_SIZE + 1;
+        let headers = build_chain(tip_number + 1);
+
+        let trusted = headers[tip_number as usize].clone();
+        let mut chain = HeaderChain::new(trusted.clone(), U256::from(trusted.number + 1));
+
+        let mut child_number = trusted.number;
+        let mut child_difficulty = U256::from(1);
+        let mut child_total_difficulty = U256::from(trusted.number + 1);
+
+        for number in (0..tip_number).rev() {
+            let header = headers[number as usize].clone();
+            chain
+                .insert_parent(header.clone(), child_number, child_difficulty, child_total_difficulty)
+                .unwrap();
+
+            if number == CHT_SECTION_SIZE {
+                assert!(
+                    chain.cht_roots().is_empty(),
+                    "the section containing the trusted tip must never be sealed"
+                );
+            }
+
+            child_total_difficulty -= child_difficulty;
+            child_difficulty = U256::from(1);
+            child_number = header.number;
+        }
+
+        assert_eq!(
+            chain.cht_roots().len(),
+            1,
+            "exactly one fully-walked section below the tip should have been sealed"
+        );
+    }
+}
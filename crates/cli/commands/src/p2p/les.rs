@@ -0,0 +1,594 @@
+//! LES (Light Ethereum Subprotocol) debugging utilities.
+//!
+//! Unlike [`super::Subcommands::Header`] and friends, which go through the full `eth` protocol
+//! handshake and the node's own network stack via `fetch_client`, this command speaks the `les/2`
+//! subprotocol directly to a single trusted light server. That makes it possible to probe a peer
+//! that only serves light-client requests, and to check that the proofs it returns for
+//! `GetProofs` actually verify against the state root of the header it also serves.
+//!
+//! `les` wraps every request/response in its own envelope: requests carry a `reqID` the server
+//! echoes back, and responses additionally carry a flow-control `BV` (buffer value). None of this
+//! overlaps with the `eth` wire types used by [`super::Subcommands`], so the message types below
+//! are distinct from (not aliases of) `reth_eth_wire`'s `eth` messages.
+//!
+//! Before any request/response traffic, both sides must exchange a `Status` message (id `0x00`):
+//! a list of `[key, value]` pairs rather than a fixed struct, since the protocol lets a peer
+//! advertise whichever optional LES extensions it supports alongside the mandatory fields. This
+//! command performs that handshake in [`LesSession::connect`] and verifies the peer's advertised
+//! genesis hash and network ID before issuing anything else.
+
+use alloy_eips::BlockHashOrNumber;
+use alloy_primitives::{keccak256, Address, Bytes, B256, U256};
+use alloy_rlp::{Decodable, Encodable, RlpDecodable, RlpEncodable};
+use alloy_trie::{nodes::TrieNode, Nibbles};
+use clap::{Parser, Subcommand};
+use futures_util::{SinkExt, StreamExt};
+use reth_cli_util::hash_or_num_value_parser;
+use reth_ecies::stream::ECIESStream;
+use reth_eth_wire::{capability::Capability, HelloMessage, P2PStream, UnauthedP2PStream};
+use reth_ethereum_primitives::{BlockBody, Header, Receipt};
+use reth_network_peers::{pk2id, NodeRecord};
+use reth_trie_common::{proof::verify_proof, TrieAccount};
+use secp256k1::{SecretKey, SECP256K1};
+use tokio::net::TcpStream;
+
+/// The capability advertised during the `p2p` `Hello` handshake for the light client
+/// subprotocol.
+const LES_CAPABILITY: &str = "les";
+/// The version of the `les` subprotocol this command speaks.
+const LES_PROTOCOL_VERSION: usize = 2;
+
+/// `reth p2p les` command
+#[derive(Debug, Parser)]
+pub struct Command {
+    /// The enode of the LES server to connect to.
+    #[arg(long)]
+    peer: NodeRecord,
+
+    /// The genesis hash to advertise in the `les` `Status` handshake, and to require of the
+    /// peer's own `Status`. Must match the peer's chain or it will reject the connection.
+    #[arg(long)]
+    genesis_hash: B256,
+
+    /// The network ID to advertise in the `les` `Status` handshake, and to require of the peer's
+    /// own `Status`.
+    #[arg(long, default_value_t = 1)]
+    network_id: u64,
+
+    #[command(subcommand)]
+    command: LesSubcommand,
+}
+
+/// `reth p2p les` subcommands, one per core LES request type.
+#[derive(Subcommand, Debug)]
+pub enum LesSubcommand {
+    /// Request one or more block headers, mirroring `eth`'s `GetBlockHeaders`.
+    GetBlockHeaders {
+        /// The header number or hash to start from.
+        #[arg(value_parser = hash_or_num_value_parser)]
+        start: BlockHashOrNumber,
+        /// The number of headers to request.
+        #[arg(long, default_value_t = 1)]
+        limit: u64,
+    },
+    /// Request one or more block bodies by hash.
+    GetBlockBodies {
+        /// The block hashes to request bodies for.
+        hashes: Vec<B256>,
+    },
+    /// Request the receipts for one or more blocks by hash.
+    GetReceipts {
+        /// The block hashes to request receipts for.
+        hashes: Vec<B256>,
+    },
+    /// Request an account or storage proof rooted at a given block's state root, and verify the
+    /// returned Merkle-Patricia nodes against that root.
+    GetProofs {
+        /// The block hash the proof should be rooted at. Its header is fetched first so the
+        /// proof can be checked against the header's `state_root`.
+        block_hash: B256,
+        /// The account to prove.
+        account: Address,
+        /// An optional storage slot to additionally prove within the account.
+        #[arg(long)]
+        storage_key: Option<B256>,
+    },
+}
+
+impl Command {
+    /// Connects to the configured LES server, issues the requested message and prints the
+    /// decoded response.
+    pub async fn execute(self) -> eyre::Result<()> {
+        let mut session =
+            LesSession::connect(self.peer, self.network_id, self.genesis_hash).await?;
+
+        match self.command {
+            LesSubcommand::GetBlockHeaders { start, limit } => {
+                let headers = session.get_block_headers(start, limit).await?;
+                println!("Received {} header(s):", headers.len());
+                for header in headers {
+                    println!("{header:?}");
+                }
+            }
+            LesSubcommand::GetBlockBodies { hashes } => {
+                let bodies = session.get_block_bodies(hashes).await?;
+                println!("Received {} body(s):", bodies.len());
+                for body in bodies {
+                    println!("{body:?}");
+                }
+            }
+            LesSubcommand::GetReceipts { hashes } => {
+                let receipts = session.get_receipts(hashes).await?;
+                println!("Received receipts for {} block(s):", receipts.len());
+                for block_receipts in receipts {
+                    println!("{block_receipts:?}");
+                }
+            }
+            LesSubcommand::GetProofs { block_hash, account, storage_key } => {
+                let header = session
+                    .get_block_headers(BlockHashOrNumber::Hash(block_hash), 1)
+                    .await?
+                    .into_iter()
+                    .next()
+                    .ok_or_else(|| eyre::eyre!("peer did not serve header {block_hash}"))?;
+
+                let account_key = Nibbles::unpack(keccak256(account));
+                let account_proof = session.get_proof(block_hash, account, None).await?;
+                let account_leaf = extract_leaf_value(&account_proof, account_key.clone())
+                    .map_err(|err| eyre::eyre!("could not locate account leaf in proof: {err}"))?;
+                verify_proof(
+                    header.state_root,
+                    account_key,
+                    Some(account_leaf.clone()),
+                    &account_proof,
+                )
+                .map_err(|err| {
+                    eyre::eyre!(
+                        "account proof for {account} failed to verify against state root {}: {err}",
+                        header.state_root
+                    )
+                })?;
+                let trie_account = TrieAccount::decode(&mut account_leaf.as_slice())?;
+                println!(
+                    "Account proof for {account} verified against state root {}: {trie_account:?}",
+                    header.state_root
+                );
+
+                if let Some(storage_key) = storage_key {
+                    let storage_trie_key = Nibbles::unpack(keccak256(storage_key));
+                    let storage_proof =
+                        session.get_proof(block_hash, account, Some(storage_key)).await?;
+                    let storage_leaf =
+                        extract_leaf_value(&storage_proof, storage_trie_key.clone()).map_err(
+                            |err| eyre::eyre!("could not locate storage leaf in proof: {err}"),
+                        )?;
+                    verify_proof(
+                        trie_account.storage_root,
+                        storage_trie_key,
+                        Some(storage_leaf.clone()),
+                        &storage_proof,
+                    )
+                    .map_err(|err| {
+                        eyre::eyre!(
+                            "storage proof for slot {storage_key} of {account} failed to verify against storage root {}: {err}",
+                            trie_account.storage_root
+                        )
+                    })?;
+                    let value = U256::decode(&mut storage_leaf.as_slice())?;
+                    println!(
+                        "Storage proof for slot {storage_key} verified against storage root {}: {value}",
+                        trie_account.storage_root
+                    );
+                }
+            }
+        }
+
+        if let Some(buffer_value) = session.last_buffer_value() {
+            println!("Server-reported flow-control buffer value: {buffer_value}");
+        }
+
+        Ok(())
+    }
+}
+
+/// A single, short-lived `les` subprotocol session with one peer.
+///
+/// This does not go through [`reth_network::NetworkManager`]; it opens a raw RLPx connection,
+/// negotiates the `les` capability during the `Hello` handshake, and then speaks the subprotocol
+/// directly, which is all the debugging use case needs.
+struct LesSession {
+    stream: P2PStream<ECIESStream<TcpStream>>,
+    /// The `reqID` of the last request sent; incremented before each request so responses can be
+    /// matched back to the request that caused them.
+    next_req_id: u64,
+    /// The flow-control buffer value (`BV`) the peer reported with its most recent response, if
+    /// any request has been answered yet.
+    last_buffer_value: Option<u64>,
+}
+
+impl LesSession {
+    /// Opens a TCP connection to `peer`, performs the ECIES and `p2p` handshakes, asserts that
+    /// the peer supports the `les` capability this command speaks, and exchanges the mandatory
+    /// `les` `Status` handshake before any request/response traffic is allowed.
+    async fn connect(peer: NodeRecord, network_id: u64, genesis_hash: B256) -> eyre::Result<Self> {
+        let key = SecretKey::new(&mut rand::thread_rng());
+        let our_peer_id = pk2id(&key.public_key(SECP256K1));
+
+        let outgoing = TcpStream::connect((peer.address, peer.tcp_port)).await?;
+        let ecies_stream = ECIESStream::connect(outgoing, key, peer.id).await?;
+
+        let hello = HelloMessage::builder(our_peer_id)
+            .capability(Capability::new_static(LES_CAPABILITY, LES_PROTOCOL_VERSION))
+            .build();
+        let (stream, their_hello) = UnauthedP2PStream::new(ecies_stream).handshake(hello).await?;
+
+        if !their_hello.capabilities.iter().any(|cap| cap.name == LES_CAPABILITY) {
+            eyre::bail!("peer {} does not support the `{LES_CAPABILITY}` capability", peer.id)
+        }
+
+        let mut session = Self { stream, next_req_id: 0, last_buffer_value: None };
+        session.negotiate_status(peer, network_id, genesis_hash).await?;
+
+        Ok(session)
+    }
+
+    /// Exchanges the `les` `Status` message (id `0x00`) both peers must send immediately after
+    /// the `p2p` `Hello`, before any other `les` message is valid. Bails if the peer's advertised
+    /// `genesisHash` or `networkId` doesn't match ours, since a real light server would otherwise
+    /// silently serve (or refuse) requests against the wrong chain.
+    async fn negotiate_status(
+        &mut self,
+        peer: NodeRecord,
+        network_id: u64,
+        genesis_hash: B256,
+    ) -> eyre::Result<()> {
+        let our_status = status_entries(network_id, genesis_hash);
+        self.send(LesMessageId::Status, &our_status).await?;
+
+        let bytes = self
+            .stream
+            .next()
+            .await
+            .ok_or_else(|| eyre::eyre!("peer closed stream before sending `Status`"))??;
+        let mut buf = bytes.as_ref();
+        let id = u8::decode(&mut buf)?;
+        if id != LesMessageId::Status as u8 {
+            eyre::bail!(
+                "expected `les` `Status` (id {}) as the first message, got id {id}",
+                LesMessageId::Status as u8
+            )
+        }
+        let their_status = Vec::<StatusEntry>::decode(&mut buf)?;
+
+        let their_genesis_hash = status_value::<B256>(&their_status, "genesisHash")?;
+        if their_genesis_hash != genesis_hash {
+            eyre::bail!(
+                "peer {} advertised genesis hash {their_genesis_hash}, expected {genesis_hash}",
+                peer.id
+            )
+        }
+        let their_network_id = status_value::<u64>(&their_status, "networkId")?;
+        if their_network_id != network_id {
+            eyre::bail!(
+                "peer {} advertised network ID {their_network_id}, expected {network_id}",
+                peer.id
+            )
+        }
+
+        let their_head_number = status_value::<u64>(&their_status, "headNum").unwrap_or_default();
+        println!("Negotiated `les` Status with {} at head block {their_head_number}", peer.id);
+
+        Ok(())
+    }
+
+    fn next_req_id(&mut self) -> u64 {
+        self.next_req_id += 1;
+        self.next_req_id
+    }
+
+    /// Returns the flow-control buffer value (`BV`) reported with the most recently received
+    /// response, if any.
+    fn last_buffer_value(&self) -> Option<u64> {
+        self.last_buffer_value
+    }
+
+    /// Issues a `GetBlockHeaders` request and returns the decoded headers.
+    async fn get_block_headers(
+        &mut self,
+        start: BlockHashOrNumber,
+        limit: u64,
+    ) -> eyre::Result<Vec<Header>> {
+        let req_id = self.next_req_id();
+        let query = GetBlockHeadersData { start, limit, skip: 0, reverse: false };
+        self.send(LesMessageId::GetBlockHeaders, &GetBlockHeadersMsg { req_id, query }).await?;
+        let response: BlockHeadersMsg = self.recv(LesMessageId::BlockHeaders, req_id).await?;
+        Ok(response.headers)
+    }
+
+    /// Issues a `GetBlockBodies` request and returns the decoded bodies.
+    async fn get_block_bodies(&mut self, hashes: Vec<B256>) -> eyre::Result<Vec<BlockBody>> {
+        let req_id = self.next_req_id();
+        self.send(LesMessageId::GetBlockBodies, &GetBlockBodiesMsg { req_id, hashes }).await?;
+        let response: BlockBodiesMsg = self.recv(LesMessageId::BlockBodies, req_id).await?;
+        Ok(response.bodies)
+    }
+
+    /// Issues a `GetReceipts` request and returns, for each requested block, its receipt list.
+    async fn get_receipts(&mut self, hashes: Vec<B256>) -> eyre::Result<Vec<Vec<Receipt>>> {
+        let req_id = self.next_req_id();
+        self.send(LesMessageId::GetReceipts, &GetReceiptsMsg { req_id, hashes }).await?;
+        let response: ReceiptsMsg = self.recv(LesMessageId::Receipts, req_id).await?;
+        Ok(response.receipts)
+    }
+
+    /// Issues a `GetProofs` request for an account, and optionally one of its storage slots,
+    /// returning the raw, flat list of trie nodes the peer claims makes up the proof.
+    async fn get_proof(
+        &mut self,
+        block_hash: B256,
+        account: Address,
+        storage_key: Option<B256>,
+    ) -> eyre::Result<Vec<Bytes>> {
+        let req_id = self.next_req_id();
+        let key = Bytes::copy_from_slice(keccak256(account).as_slice());
+        let key2 = storage_key
+            .map(|slot| Bytes::copy_from_slice(keccak256(slot).as_slice()))
+            .unwrap_or_default();
+        let request = ProofRequest { block_hash, key, key2, from_level: 0 };
+        self.send(LesMessageId::GetProofs, &GetProofsMsg { req_id, requests: vec![request] })
+            .await?;
+        let response: ProofsMsg = self.recv(LesMessageId::Proofs, req_id).await?;
+        Ok(response.nodes)
+    }
+
+    async fn send<M: Encodable>(&mut self, id: LesMessageId, message: &M) -> eyre::Result<()> {
+        let mut buf = Vec::with_capacity(1 + message.length());
+        (id as u8).encode(&mut buf);
+        message.encode(&mut buf);
+        self.stream.send(bytes::Bytes::from(buf)).await.map_err(Into::into)
+    }
+
+    /// Receives a `[reqID, BV, ...]`-enveloped response, checks its `reqID` echoes `req_id`, and
+    /// records its `BV` as the session's most recent flow-control buffer value.
+    async fn recv<M: Decodable + LesResponse>(
+        &mut self,
+        expected: LesMessageId,
+        req_id: u64,
+    ) -> eyre::Result<M> {
+        let bytes = self
+            .stream
+            .next()
+            .await
+            .ok_or_else(|| eyre::eyre!("peer closed stream"))??;
+        let mut buf = bytes.as_ref();
+        let id = u8::decode(&mut buf)?;
+        if id != expected as u8 {
+            eyre::bail!("unexpected message id {id}, expected {}", expected as u8)
+        }
+        let response = M::decode(&mut buf)?;
+        if response.req_id() != req_id {
+            eyre::bail!("reqID mismatch: sent {req_id}, received {}", response.req_id())
+        }
+        self.last_buffer_value = Some(response.buffer_value());
+        Ok(response)
+    }
+}
+
+/// Walks a flat Merkle-Patricia proof (as returned by LES `GetProofs`) from its root, following
+/// `path`, and returns the raw value stored at the leaf it terminates in.
+///
+/// This only extracts the claimed value; [`verify_proof`] is what actually re-derives the root
+/// hash from these same nodes to confirm the peer isn't lying about the root or the path.
+fn extract_leaf_value(proof: &[Bytes], mut path: Nibbles) -> eyre::Result<Vec<u8>> {
+    for node_bytes in proof {
+        let node = TrieNode::decode(&mut node_bytes.as_ref())?;
+        match node {
+            TrieNode::Branch(branch) => {
+                let Some(&nibble) = path.first() else {
+                    eyre::bail!("proof path exhausted at a branch node")
+                };
+                if !branch.state_mask.is_bit_set(nibble) {
+                    eyre::bail!("branch node has no child at nibble {nibble}; key does not exist")
+                }
+                path = Nibbles::from_nibbles(&path.as_slice()[1..]);
+            }
+            TrieNode::Extension(ext) => {
+                if !path.as_slice().starts_with(ext.key.as_slice()) {
+                    eyre::bail!("extension node key does not match the remaining path")
+                }
+                path = Nibbles::from_nibbles(&path.as_slice()[ext.key.len()..]);
+            }
+            TrieNode::Leaf(leaf) => {
+                if path.as_slice() != leaf.key.as_slice() {
+                    eyre::bail!("leaf node key does not match the remaining path")
+                }
+                return Ok(leaf.value.clone())
+            }
+            TrieNode::EmptyRoot => {
+                eyre::bail!("encountered an empty trie root while a value was expected")
+            }
+        }
+    }
+
+    eyre::bail!("proof ended before reaching a leaf node")
+}
+
+/// Message IDs for the subset of the `les/2` subprotocol this command supports.
+#[derive(Debug, Clone, Copy)]
+#[repr(u8)]
+enum LesMessageId {
+    Status = 0x00,
+    GetBlockHeaders = 0x02,
+    BlockHeaders = 0x03,
+    GetBlockBodies = 0x04,
+    BlockBodies = 0x05,
+    GetReceipts = 0x06,
+    Receipts = 0x07,
+    GetProofs = 0x0f,
+    Proofs = 0x10,
+}
+
+/// A response to a `les` request, each of which is enveloped as `[reqID, BV, ...]`.
+trait LesResponse {
+    /// The echoed `reqID` of the request this responds to.
+    fn req_id(&self) -> u64;
+    /// The flow-control buffer value (`BV`) the peer reports alongside the response.
+    fn buffer_value(&self) -> u64;
+}
+
+/// A single `key`/`value` pair of the `les` `Status` handshake message, where `value` is itself
+/// the RLP encoding of whatever type `key` indicates (e.g. `U256` for `headTd`, `B256` for
+/// `genesisHash`).
+#[derive(Debug, Clone, RlpEncodable, RlpDecodable)]
+struct StatusEntry {
+    key: String,
+    value: Bytes,
+}
+
+/// Builds the `les` `Status` entries this command advertises: a minimal, stateless light client
+/// with no head of its own, existing only to prove what the peer itself serves.
+fn status_entries(network_id: u64, genesis_hash: B256) -> Vec<StatusEntry> {
+    let encode = |value: &dyn Encodable| {
+        let mut buf = Vec::new();
+        value.encode(&mut buf);
+        Bytes::from(buf)
+    };
+
+    vec![
+        StatusEntry { key: "protocolVersion".into(), value: encode(&(LES_PROTOCOL_VERSION as u64)) },
+        StatusEntry { key: "networkId".into(), value: encode(&network_id) },
+        StatusEntry { key: "headTd".into(), value: encode(&U256::ZERO) },
+        StatusEntry { key: "headHash".into(), value: encode(&B256::ZERO) },
+        StatusEntry { key: "headNum".into(), value: encode(&0u64) },
+        StatusEntry { key: "genesisHash".into(), value: encode(&genesis_hash) },
+    ]
+}
+
+/// Looks up and decodes `key` out of a peer's `Status` entries.
+fn status_value<T: Decodable>(entries: &[StatusEntry], key: &str) -> eyre::Result<T> {
+    let entry = entries
+        .iter()
+        .find(|entry| entry.key == key)
+        .ok_or_else(|| eyre::eyre!("peer `Status` is missing `{key}`"))?;
+    Ok(T::decode(&mut entry.value.as_ref())?)
+}
+
+/// The `[block, maxHeaders, skip, reverse]` query embedded in a `les` `GetBlockHeaders` request.
+#[derive(Debug, Clone, RlpEncodable)]
+struct GetBlockHeadersData {
+    start: BlockHashOrNumber,
+    limit: u64,
+    skip: u64,
+    reverse: bool,
+}
+
+/// `les` `GetBlockHeaders`: `[reqID, [block, maxHeaders, skip, reverse]]`.
+#[derive(Debug, Clone, RlpEncodable)]
+struct GetBlockHeadersMsg {
+    req_id: u64,
+    query: GetBlockHeadersData,
+}
+
+/// `les` `BlockHeaders`: `[reqID, BV, [header, ...]]`.
+#[derive(Debug, Clone, RlpDecodable)]
+struct BlockHeadersMsg {
+    req_id: u64,
+    buffer_value: u64,
+    headers: Vec<Header>,
+}
+
+impl LesResponse for BlockHeadersMsg {
+    fn req_id(&self) -> u64 {
+        self.req_id
+    }
+
+    fn buffer_value(&self) -> u64 {
+        self.buffer_value
+    }
+}
+
+/// `les` `GetBlockBodies`: `[reqID, [hash, ...]]`.
+#[derive(Debug, Clone, RlpEncodable)]
+struct GetBlockBodiesMsg {
+    req_id: u64,
+    hashes: Vec<B256>,
+}
+
+/// `les` `BlockBodies`: `[reqID, BV, [body, ...]]`.
+#[derive(Debug, Clone, RlpDecodable)]
+struct BlockBodiesMsg {
+    req_id: u64,
+    buffer_value: u64,
+    bodies: Vec<BlockBody>,
+}
+
+impl LesResponse for BlockBodiesMsg {
+    fn req_id(&self) -> u64 {
+        self.req_id
+    }
+
+    fn buffer_value(&self) -> u64 {
+        self.buffer_value
+    }
+}
+
+/// `les` `GetReceipts`: `[reqID, [hash, ...]]`.
+#[derive(Debug, Clone, RlpEncodable)]
+struct GetReceiptsMsg {
+    req_id: u64,
+    hashes: Vec<B256>,
+}
+
+/// `les` `Receipts`: `[reqID, BV, [[receipt, ...], ...]]`, one receipt list per requested block.
+#[derive(Debug, Clone, RlpDecodable)]
+struct ReceiptsMsg {
+    req_id: u64,
+    buffer_value: u64,
+    receipts: Vec<Vec<Receipt>>,
+}
+
+impl LesResponse for ReceiptsMsg {
+    fn req_id(&self) -> u64 {
+        self.req_id
+    }
+
+    fn buffer_value(&self) -> u64 {
+        self.buffer_value
+    }
+}
+
+/// A single `les` `GetProofs` entry: prove `key` (and, for a storage proof, `key2` within it)
+/// against the state root of `block_hash`.
+#[derive(Debug, Clone, RlpEncodable)]
+struct ProofRequest {
+    block_hash: B256,
+    key: Bytes,
+    key2: Bytes,
+    from_level: u64,
+}
+
+/// `les` `GetProofsV2`: `[reqID, [[blockHash, key, key2, fromLevel], ...]]`.
+#[derive(Debug, Clone, RlpEncodable)]
+struct GetProofsMsg {
+    req_id: u64,
+    requests: Vec<ProofRequest>,
+}
+
+/// `les` `ProofsV2`: `[reqID, BV, [node, ...]]` — a flat list of trie nodes the client walks
+/// itself, rather than a pre-assembled per-account structure.
+#[derive(Debug, Clone, RlpDecodable)]
+struct ProofsMsg {
+    req_id: u64,
+    buffer_value: u64,
+    nodes: Vec<Bytes>,
+}
+
+impl LesResponse for ProofsMsg {
+    fn req_id(&self) -> u64 {
+        self.req_id
+    }
+
+    fn buffer_value(&self) -> u64 {
+        self.buffer_value
+    }
+}
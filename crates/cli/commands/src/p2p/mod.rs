@@ -1,25 +1,63 @@
 //! P2P Debugging tool
 
-use std::{path::PathBuf, sync::Arc};
+use std::{ops::RangeInclusive, path::PathBuf, sync::Arc};
 
 use crate::common::CliNodeTypes;
+use alloy_consensus::proofs::calculate_receipt_root;
 use alloy_eips::BlockHashOrNumber;
+use alloy_primitives::{B256, U256};
 use backon::{ConstantBuilder, Retryable};
 use clap::{Parser, Subcommand};
+use header_chain::HeaderChain;
+use output::{OutputFormat, OutputWriter};
 use reth_chainspec::{EthChainSpec, EthereumHardforks, Hardforks};
 use reth_cli::chainspec::ChainSpecParser;
 use reth_cli_util::{get_secret_key, hash_or_num_value_parser};
 use reth_config::Config;
 use reth_network::{BlockDownloaderProvider, NetworkConfigBuilder};
-use reth_network_p2p::bodies::client::BodiesClient;
+use reth_network_p2p::{
+    bodies::client::BodiesClient,
+    headers::client::{HeadersClient, HeadersDirection, HeadersRequest},
+    receipts::client::ReceiptsClient,
+};
 use reth_node_core::{
     args::{DatadirArgs, NetworkArgs},
     utils::get_single_header,
 };
 
 pub mod bootnode;
+pub mod header_chain;
+pub mod les;
+pub mod output;
 pub mod rlpx;
 
+/// The maximum number of headers requested in a single `GetBlockHeaders` message, mirroring the
+/// limit peers enforce on the eth wire protocol.
+const MAX_HEADERS_PER_CHUNK: u64 = 1_024;
+
+/// The maximum number of bodies requested in a single `GetBlockBodies` message. Bodies are much
+/// larger than headers on the wire, so peers tend to enforce a tighter cap here.
+const MAX_BODIES_PER_CHUNK: u64 = 128;
+
+/// Splits an inclusive block-number range into consecutive, non-overlapping chunks of at most
+/// `chunk_size` blocks each, preserving order.
+fn chunk_range(
+    range: RangeInclusive<u64>,
+    chunk_size: u64,
+) -> impl Iterator<Item = RangeInclusive<u64>> {
+    let mut next = *range.start();
+    let end = *range.end();
+    std::iter::from_fn(move || {
+        if next > end {
+            return None
+        }
+        let chunk_end = next.saturating_add(chunk_size - 1).min(end);
+        let chunk = next..=chunk_end;
+        next = chunk_end + 1;
+        Some(chunk)
+    })
+}
+
 /// `reth p2p` command
 #[derive(Debug, Parser)]
 pub struct Command<C: ChainSpecParser> {
@@ -31,57 +69,237 @@ impl<C: ChainSpecParser<ChainSpec: EthChainSpec + Hardforks + EthereumHardforks>
     /// Execute `p2p` command
     pub async fn execute<N: CliNodeTypes<ChainSpec = C::ChainSpec>>(self) -> eyre::Result<()> {
         match self.command {
-            Subcommands::Header { args, id } => {
+            Subcommands::Header { args, range } => {
                 let handle = args.launch_network::<N>().await?;
                 let fetch_client = handle.fetch_client().await?;
                 let backoff = args.backoff();
+                let mut writer = args.output_writer()?;
 
-                let header = (move || get_single_header(fetch_client.clone(), id))
-                    .retry(backoff)
-                    .notify(|err, _| println!("Error requesting header: {err}. Retrying..."))
-                    .await?;
-                println!("Successfully downloaded header: {header:?}");
+                if let Some(range) = range.range() {
+                    for chunk in chunk_range(range, MAX_HEADERS_PER_CHUNK) {
+                        let client = fetch_client.clone();
+                        let request = HeadersRequest {
+                            start: BlockHashOrNumber::Number(*chunk.start()),
+                            limit: chunk.end() - chunk.start() + 1,
+                            direction: HeadersDirection::Rising,
+                        };
+                        let (_, headers) = (move || client.clone().get_headers(request.clone()))
+                            .retry(backoff)
+                            .notify(|err, _| {
+                                println!("Error requesting headers {chunk:?}: {err}. Retrying...")
+                            })
+                            .await?
+                            .split();
+                        for header in headers {
+                            println!("Successfully downloaded header: {header:?}");
+                            if let Some(writer) = writer.as_mut() {
+                                writer.write_item(&header)?;
+                            }
+                        }
+                    }
+                } else {
+                    let id = range.id();
+                    let header = (move || get_single_header(fetch_client.clone(), id))
+                        .retry(backoff)
+                        .notify(|err, _| println!("Error requesting header: {err}. Retrying..."))
+                        .await?;
+                    println!("Successfully downloaded header: {header:?}");
+                    if let Some(writer) = writer.as_mut() {
+                        writer.write_item(&header)?;
+                    }
+                }
             }
 
-            Subcommands::Body { args, id } => {
+            Subcommands::Body { args, range } => {
                 let handle = args.launch_network::<N>().await?;
                 let fetch_client = handle.fetch_client().await?;
                 let backoff = args.backoff();
+                let mut writer = args.output_writer()?;
 
-                let hash = match id {
-                    BlockHashOrNumber::Hash(hash) => hash,
-                    BlockHashOrNumber::Number(number) => {
-                        println!("Block number provided. Downloading header first...");
+                if let Some(range) = range.range() {
+                    for header_chunk in chunk_range(range, MAX_HEADERS_PER_CHUNK) {
                         let client = fetch_client.clone();
-                        let header = (move || {
-                            get_single_header(client.clone(), BlockHashOrNumber::Number(number))
-                        })
-                        .retry(backoff)
-                        .notify(|err, _| println!("Error requesting header: {err}. Retrying..."))
-                        .await?;
-                        header.hash()
+                        let request = HeadersRequest {
+                            start: BlockHashOrNumber::Number(*header_chunk.start()),
+                            limit: header_chunk.end() - header_chunk.start() + 1,
+                            direction: HeadersDirection::Rising,
+                        };
+                        let (_, headers) = (move || client.clone().get_headers(request.clone()))
+                            .retry(backoff)
+                            .notify(|err, _| {
+                                println!(
+                                    "Error requesting headers {header_chunk:?}: {err}. Retrying..."
+                                )
+                            })
+                            .await?
+                            .split();
+                        let hashes: Vec<_> = headers.iter().map(|header| header.hash_slow()).collect();
+
+                        for body_chunk in hashes.chunks(MAX_BODIES_PER_CHUNK as usize) {
+                            let client = fetch_client.clone();
+                            let hashes = body_chunk.to_vec();
+                            let (_, bodies) = (move || client.clone().get_block_bodies(hashes.clone()))
+                                .retry(backoff)
+                                .notify(|err, _| {
+                                    println!("Error requesting bodies: {err}. Retrying...")
+                                })
+                                .await?
+                                .split();
+                            for body in bodies {
+                                println!("Successfully downloaded body: {body:?}");
+                                if let Some(writer) = writer.as_mut() {
+                                    writer.write_item(&body)?;
+                                }
+                            }
+                        }
                     }
-                };
+                } else {
+                    let id = range.id();
+                    let hash = match id {
+                        BlockHashOrNumber::Hash(hash) => hash,
+                        BlockHashOrNumber::Number(number) => {
+                            println!("Block number provided. Downloading header first...");
+                            let client = fetch_client.clone();
+                            let header = (move || {
+                                get_single_header(
+                                    client.clone(),
+                                    BlockHashOrNumber::Number(number),
+                                )
+                            })
+                            .retry(backoff)
+                            .notify(|err, _| {
+                                println!("Error requesting header: {err}. Retrying...")
+                            })
+                            .await?;
+                            header.hash()
+                        }
+                    };
+                    let (_, result) = (move || {
+                        let client = fetch_client.clone();
+                        client.get_block_bodies(vec![hash])
+                    })
+                    .retry(backoff)
+                    .notify(|err, _| println!("Error requesting block: {err}. Retrying..."))
+                    .await?
+                    .split();
+                    if result.len() != 1 {
+                        eyre::bail!(
+                            "Invalid number of bodies received. Expected: 1. Received: {}",
+                            result.len()
+                        )
+                    }
+                    let body = result.into_iter().next().unwrap();
+                    println!("Successfully downloaded body: {body:?}");
+                    if let Some(writer) = writer.as_mut() {
+                        writer.write_item(&body)?;
+                    }
+                }
+            }
+
+            Subcommands::Receipts { args, id } => {
+                let handle = args.launch_network::<N>().await?;
+                let fetch_client = handle.fetch_client().await?;
+                let backoff = args.backoff();
+                let mut writer = args.output_writer()?;
+
+                let client = fetch_client.clone();
+                let header = (move || get_single_header(client.clone(), id))
+                    .retry(backoff)
+                    .notify(|err, _| println!("Error requesting header: {err}. Retrying..."))
+                    .await?;
+                let hash = header.hash_slow();
+
                 let (_, result) = (move || {
                     let client = fetch_client.clone();
-                    client.get_block_bodies(vec![hash])
+                    client.get_receipts(vec![hash])
                 })
                 .retry(backoff)
-                .notify(|err, _| println!("Error requesting block: {err}. Retrying..."))
+                .notify(|err, _| println!("Error requesting receipts: {err}. Retrying..."))
                 .await?
                 .split();
                 if result.len() != 1 {
                     eyre::bail!(
-                        "Invalid number of headers received. Expected: 1. Received: {}",
+                        "Invalid number of receipt lists received. Expected: 1. Received: {}",
                         result.len()
                     )
                 }
-                let body = result.into_iter().next().unwrap();
-                println!("Successfully downloaded body: {body:?}")
+                let receipts = result.into_iter().next().unwrap();
+
+                let computed_root = calculate_receipt_root(&receipts);
+                if computed_root != header.receipts_root {
+                    eyre::bail!(
+                        "Receipts root mismatch for block {hash}: computed {computed_root}, header has {}",
+                        header.receipts_root
+                    )
+                }
+                println!("Receipts root verified against header: {computed_root}");
+                println!("Successfully downloaded receipts: {receipts:?}");
+                if let Some(writer) = writer.as_mut() {
+                    for receipt in &receipts {
+                        writer.write_consensus_item(receipt)?;
+                    }
+                }
+            }
+            Subcommands::CheckpointSync { args, trusted_hash, trusted_total_difficulty, depth } => {
+                let handle = args.launch_network::<N>().await?;
+                let fetch_client = handle.fetch_client().await?;
+                let backoff = args.backoff();
+
+                let trusted = (move || {
+                    get_single_header(fetch_client.clone(), BlockHashOrNumber::Hash(trusted_hash))
+                })
+                .retry(backoff)
+                .notify(|err, _| println!("Error requesting trusted header: {err}. Retrying..."))
+                .await?;
+                println!(
+                    "Starting checkpoint sync from trusted header {trusted_hash} (number {})",
+                    trusted.number
+                );
+
+                let target = trusted.number.saturating_sub(depth);
+                let mut chain = HeaderChain::new(trusted.clone(), trusted_total_difficulty);
+                let mut parent_hash = trusted.parent_hash;
+                let mut child_number = trusted.number;
+                let mut child_difficulty = U256::from(trusted.difficulty);
+                let mut child_total_difficulty = trusted_total_difficulty;
+
+                while child_number > target {
+                    let client = fetch_client.clone();
+                    let hash = parent_hash;
+                    let header = (move || {
+                        get_single_header(client.clone(), BlockHashOrNumber::Hash(hash))
+                    })
+                    .retry(backoff)
+                    .notify(|err, _| println!("Error requesting header {hash}: {err}. Retrying..."))
+                    .await?;
+
+                    chain.insert_parent(
+                        header.clone(),
+                        child_number,
+                        child_difficulty,
+                        child_total_difficulty,
+                    )?;
+                    child_total_difficulty -= child_difficulty;
+                    child_difficulty = U256::from(header.difficulty);
+                    parent_hash = header.parent_hash;
+                    child_number = header.number;
+                }
+
+                println!(
+                    "Walked back to block {child_number}, best block: {:?}",
+                    chain.best_block()
+                );
+                println!("Sealed CHT roots:");
+                for (index, root) in chain.cht_roots().iter().enumerate() {
+                    println!("  section {index}: {root}");
+                }
             }
             Subcommands::Rlpx(command) => {
                 command.execute().await?;
             }
+            Subcommands::Les(command) => {
+                command.execute().await?;
+            }
             Subcommands::Bootnode(command) => {
                 command.execute().await?;
             }
@@ -97,7 +315,10 @@ impl<C: ChainSpecParser> Command<C> {
         match &self.command {
             Subcommands::Header { args, .. } => Some(&args.chain),
             Subcommands::Body { args, .. } => Some(&args.chain),
+            Subcommands::Receipts { args, .. } => Some(&args.chain),
+            Subcommands::CheckpointSync { args, .. } => Some(&args.chain),
             Subcommands::Rlpx(_) => None,
+            Subcommands::Les(_) => None,
             Subcommands::Bootnode(_) => None,
         }
     }
@@ -106,28 +327,96 @@ impl<C: ChainSpecParser> Command<C> {
 /// `reth p2p` subcommands
 #[derive(Subcommand, Debug)]
 pub enum Subcommands<C: ChainSpecParser> {
-    /// Download block header
+    /// Download block header(s)
     Header {
         #[command(flatten)]
         args: DownloadArgs<C>,
-        /// The header number or hash
-        #[arg(value_parser = hash_or_num_value_parser)]
-        id: BlockHashOrNumber,
+        /// The header number, hash, or an inclusive `--from`/`--to` range to download
+        #[command(flatten)]
+        range: BlockRangeArgs,
     },
-    /// Download block body
+    /// Download block body(s)
     Body {
+        #[command(flatten)]
+        args: DownloadArgs<C>,
+        /// The block number, hash, or an inclusive `--from`/`--to` range to download
+        #[command(flatten)]
+        range: BlockRangeArgs,
+    },
+    /// Download receipts for a block, verifying them against the header's `receipts_root`
+    Receipts {
         #[command(flatten)]
         args: DownloadArgs<C>,
         /// The block number or hash
         #[arg(value_parser = hash_or_num_value_parser)]
         id: BlockHashOrNumber,
     },
+    /// Walk headers backward from a trusted block hash, verifying parent links and sealing CHT
+    /// checkpoint roots, without touching the local database.
+    CheckpointSync {
+        #[command(flatten)]
+        args: DownloadArgs<C>,
+        /// The hash of a block you trust to be canonical; headers are walked backward from here.
+        trusted_hash: B256,
+        /// The known total difficulty of `trusted_hash`. CHT leaves store cumulative total
+        /// difficulty, which can't be derived by walking backward without an anchor.
+        #[arg(long)]
+        trusted_total_difficulty: U256,
+        /// How many blocks to walk backward from `trusted_hash`.
+        #[arg(long, default_value_t = header_chain::CHT_SECTION_SIZE)]
+        depth: u64,
+    },
     // RLPx utilities
     Rlpx(rlpx::Command),
+    /// LES (Light Ethereum Subprotocol) utilities
+    Les(les::Command),
     /// Bootnode command
     Bootnode(bootnode::Command),
 }
 
+/// Either a single block identifier or an inclusive block-number range, shared by the `header`
+/// and `body` subcommands so that a single invocation can stream many items in order.
+///
+/// A range is given with `--from <NUM> --to <NUM>`; a single item is given positionally as
+/// either a block number or a block hash.
+#[derive(Debug, Clone, Parser)]
+pub struct BlockRangeArgs {
+    /// The header number or hash
+    #[arg(value_parser = hash_or_num_value_parser, required_unless_present = "from")]
+    id: Option<BlockHashOrNumber>,
+
+    /// The first block number of an inclusive range to download, e.g. `--from 1000 --to 1200`
+    #[arg(long, requires = "to", conflicts_with = "id")]
+    from: Option<u64>,
+
+    /// The last block number of an inclusive range to download
+    #[arg(long, requires = "from", conflicts_with = "id")]
+    to: Option<u64>,
+}
+
+impl BlockRangeArgs {
+    /// Returns the inclusive block-number range requested via `--from`/`--to`, if any.
+    fn range(&self) -> Option<RangeInclusive<u64>> {
+        match (self.from, self.to) {
+            (Some(from), Some(to)) => Some(from..=to),
+            _ => None,
+        }
+    }
+
+    /// Returns the single block identifier requested, falling back to the start of the range.
+    ///
+    /// # Panics
+    ///
+    /// Panics if neither a single identifier nor a `--from`/`--to` range was provided; clap's
+    /// `required_unless_present`/`requires`/`conflicts_with` constraints guarantee this never
+    /// happens in practice.
+    fn id(&self) -> BlockHashOrNumber {
+        self.id.unwrap_or_else(|| {
+            BlockHashOrNumber::Number(self.from.expect("either id or from/to must be set"))
+        })
+    }
+}
+
 #[derive(Debug, Clone, Parser)]
 pub struct DownloadArgs<C: ChainSpecParser> {
     /// The number of retries per request
@@ -140,6 +429,14 @@ pub struct DownloadArgs<C: ChainSpecParser> {
     #[command(flatten)]
     datadir: DatadirArgs,
 
+    /// Write downloaded items to this file instead of only printing them.
+    #[arg(long, value_name = "FILE")]
+    output: Option<PathBuf>,
+
+    /// The encoding to use for `--output`.
+    #[arg(long, value_enum, default_value_t = OutputFormat::Json, requires = "output")]
+    format: OutputFormat,
+
     /// The path to the configuration file to use.
     #[arg(long, value_name = "FILE", verbatim_doc_comment)]
     config: Option<PathBuf>,
@@ -208,6 +505,12 @@ impl<C: ChainSpecParser> DownloadArgs<C> {
     pub fn backoff(&self) -> ConstantBuilder {
         ConstantBuilder::default().with_max_times(self.retries.max(1))
     }
+
+    /// Opens the `--output` file, if set, returning a writer that persists downloaded items in
+    /// the configured `--format`.
+    pub fn output_writer(&self) -> eyre::Result<Option<OutputWriter>> {
+        self.output.as_deref().map(|path| OutputWriter::create(path, self.format)).transpose()
+    }
 }
 
 #[cfg(test)]
@@ -226,4 +529,61 @@ mod tests {
         let _args: Command<EthereumChainSpecParser> =
             Command::parse_from(["reth", "body", "--chain", "mainnet", "1000"]);
     }
+
+    #[test]
+    fn parse_header_range_cmd() {
+        let _args: Command<EthereumChainSpecParser> = Command::parse_from([
+            "reth",
+            "header",
+            "--chain",
+            "mainnet",
+            "--from",
+            "1000",
+            "--to",
+            "1200",
+        ]);
+    }
+
+    #[test]
+    fn parse_body_range_cmd() {
+        let _args: Command<EthereumChainSpecParser> = Command::parse_from([
+            "reth", "body", "--chain", "mainnet", "--from", "1000", "--to", "1200",
+        ]);
+    }
+
+    #[test]
+    fn parse_header_output_cmd() {
+        let _args: Command<EthereumChainSpecParser> = Command::parse_from([
+            "reth",
+            "header",
+            "--chain",
+            "mainnet",
+            "--output",
+            "headers.rlp",
+            "--format",
+            "rlp",
+            "1000",
+        ]);
+    }
+
+    #[test]
+    fn parse_receipts_cmd() {
+        let _args: Command<EthereumChainSpecParser> =
+            Command::parse_from(["reth", "receipts", "--chain", "mainnet", "1000"]);
+    }
+
+    #[test]
+    fn parse_checkpoint_sync_cmd() {
+        let _args: Command<EthereumChainSpecParser> = Command::parse_from([
+            "reth",
+            "checkpoint-sync",
+            "--chain",
+            "mainnet",
+            "0x0000000000000000000000000000000000000000000000000000000000000001",
+            "--trusted-total-difficulty",
+            "58750003716598352816469",
+            "--depth",
+            "4096",
+        ]);
+    }
 }
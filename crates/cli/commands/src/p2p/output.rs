@@ -0,0 +1,77 @@
+//! Persisting downloaded headers, bodies and receipts to disk, so `reth p2p` can be used to
+//! capture fixtures instead of only printing items to the terminal.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use alloy_eips::eip2718::Encodable2718;
+use alloy_rlp::Encodable;
+use clap::ValueEnum;
+use serde::Serialize;
+
+/// The on-disk encoding used by [`OutputWriter`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Canonical consensus RLP encoding, concatenated back-to-back. Byte-for-byte comparable
+    /// against another client's encoding of the same data.
+    Rlp,
+    /// One JSON value per line, for human inspection.
+    Json,
+}
+
+/// Appends downloaded items to a file in the configured [`OutputFormat`].
+#[derive(Debug)]
+pub struct OutputWriter {
+    file: BufWriter<File>,
+    format: OutputFormat,
+}
+
+impl OutputWriter {
+    /// Creates (or truncates) the file at `path` and returns a writer for it.
+    pub fn create(path: &Path, format: OutputFormat) -> eyre::Result<Self> {
+        let file = OpenOptions::new().create(true).write(true).truncate(true).open(path)?;
+        Ok(Self { file: BufWriter::new(file), format })
+    }
+
+    /// Writes a single downloaded item with no EIP-2718 type envelope (e.g. a header or a body,
+    /// whose own `Encodable` impl already nests any typed transactions correctly) in the
+    /// configured format.
+    pub fn write_item<T: Encodable + Serialize>(&mut self, item: &T) -> eyre::Result<()> {
+        match self.format {
+            OutputFormat::Rlp => {
+                let mut buf = Vec::with_capacity(item.length());
+                item.encode(&mut buf);
+                self.file.write_all(&buf)?;
+            }
+            OutputFormat::Json => {
+                serde_json::to_writer(&mut self.file, item)?;
+                self.file.write_all(b"\n")?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes a single downloaded item that carries an EIP-2718 type byte (e.g. a typed receipt)
+    /// in the configured format, using its 2718 consensus encoding rather than plain RLP so the
+    /// on-disk bytes match what another client would produce.
+    pub fn write_consensus_item<T: Encodable2718 + Serialize>(
+        &mut self,
+        item: &T,
+    ) -> eyre::Result<()> {
+        match self.format {
+            OutputFormat::Rlp => {
+                let mut buf = Vec::with_capacity(item.encode_2718_len());
+                item.encode_2718(&mut buf);
+                self.file.write_all(&buf)?;
+            }
+            OutputFormat::Json => {
+                serde_json::to_writer(&mut self.file, item)?;
+                self.file.write_all(b"\n")?;
+            }
+        }
+        Ok(())
+    }
+}